@@ -3,7 +3,10 @@ use reqwest::Client;
 use serde_json::Value;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{collections::HashSet, io::Cursor};
+use tokio::sync::Semaphore;
 
 use log::{self, debug, error, info, warn};
 
@@ -26,8 +29,18 @@ struct Args {
     /// (optional) If you used the default NGINX proxy config specify user and password.
     #[arg(short, long)]
     password: Option<String>,
+
+    /// Maximum number of downloads to run at once.
+    #[arg(short, long, default_value_t = 8)]
+    concurrency: usize,
 }
 
+/// Maximum number of retry attempts for a single download before giving up on it.
+const MAX_DOWNLOAD_RETRIES: u32 = 3;
+
+/// Delay before the first retry; doubles after each subsequent failed attempt.
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
 #[tokio::main]
 async fn main() {
     init_logging();
@@ -45,7 +58,7 @@ async fn main() {
             for img in &images {
                 info!("{:?}", img);
             }
-            if let Err(e) = sync_local(image_directory, images, &auth).await {
+            if let Err(e) = sync_local(image_directory, images, &auth, args.concurrency).await {
                 error!("Failed to sync local with remote: {e}");
             }
         }
@@ -130,12 +143,12 @@ async fn fetch_image_links(
     }
 
     let json: Value = response.json().await?;
-    let images_filenames: &Vec<Value> = json["images"].as_array().ok_or("JSON is not an array")?; // A list of filenames (file1.png, file2.jpg)
+    let images_entries: &Vec<Value> = json["images"].as_array().ok_or("JSON is not an array")?; // A list of {"filename": ..., "blurhash": ...} entries
 
     let mut result: Vec<Image> = Vec::new();
 
-    for img_filename in images_filenames {
-        let filename = img_filename
+    for entry in images_entries {
+        let filename = entry["filename"]
             .as_str()
             .ok_or("Image filename is not a string")?
             .to_string();
@@ -162,6 +175,7 @@ async fn sync_local<P>(
     directory: P,
     images: Vec<Image>,
     auth: &Option<Authentication>,
+    concurrency: usize,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     P: AsRef<Path> + std::fmt::Debug,
@@ -182,6 +196,7 @@ where
 
     // Add images
     let mut tasks: Vec<_> = Vec::new();
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
 
     let mut images_filenames: HashSet<String> = HashSet::new();
     for img in images {
@@ -189,8 +204,13 @@ where
         if !local_filenames.contains(&img.filename) {
             let path = Path::new(directory.as_ref()).join(&img.filename);
             let auth_clone = auth.clone();
+            let semaphore = semaphore.clone();
             tasks.push(tokio::spawn(async move {
-                match download_file(&path, &img.download_link, &auth_clone).await {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .expect("download semaphore should never be closed");
+                match download_with_retry(&path, &img.download_link, &auth_clone).await {
                     Ok(_) => {
                         info!("Successfully downloaded: {:?}", path);
                     }
@@ -203,7 +223,7 @@ where
         }
     }
 
-    // Download images asynchronously (nice)
+    // Download images asynchronously, bounded by `concurrency` simultaneous downloads.
     futures::future::join_all(tasks).await;
 
     // Remove images
@@ -226,7 +246,46 @@ fn ensure_directory_exists<P: AsRef<Path>>(directory: P) -> std::io::Result<()>
     Ok(())
 }
 
-/// Downloads a file from a URL and saves it at `path` which also contains the file name
+/// Downloads `url` to `path`, retrying transient failures with exponential backoff up to
+/// `MAX_DOWNLOAD_RETRIES` times.
+async fn download_with_retry<P>(
+    path: P,
+    url: &str,
+    auth: &Option<Authentication>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    P: AsRef<Path> + std::fmt::Debug,
+{
+    let mut backoff = INITIAL_RETRY_BACKOFF;
+
+    for attempt in 0..=MAX_DOWNLOAD_RETRIES {
+        match download_file(&path, url, auth).await {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < MAX_DOWNLOAD_RETRIES => {
+                // `e` (`Box<dyn std::error::Error>`) isn't `Send`, so it's converted to an
+                // owned `String` here rather than held live across the `.await` below —
+                // otherwise this function's future isn't `Send` and can't be `tokio::spawn`ed.
+                let message = e.to_string();
+                warn!(
+                    "Download attempt {}/{} failed for {:?}: {message}. Retrying in {:?}",
+                    attempt + 1,
+                    MAX_DOWNLOAD_RETRIES + 1,
+                    path,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("loop always returns on its last iteration")
+}
+
+/// Downloads a file from a URL and saves it at `path` which also contains the file name.
+/// Verifies the downloaded byte count against the server's advertised `Content-Length` so
+/// partial or corrupt downloads are detected instead of silently kept.
 async fn download_file<P>(
     path: P,
     url: &str,
@@ -248,8 +307,22 @@ where
         return Err(format!("Failed to download file: HTTP {}", response.status()).into());
     }
 
+    let expected_len = response.content_length();
+    let bytes = response.bytes().await?;
+
+    if let Some(expected_len) = expected_len {
+        if bytes.len() as u64 != expected_len {
+            return Err(format!(
+                "Downloaded {} bytes but server advertised {} bytes",
+                bytes.len(),
+                expected_len
+            )
+            .into());
+        }
+    }
+
     let mut file = std::fs::File::create(&path)?;
-    let mut content = Cursor::new(response.bytes().await?);
+    let mut content = Cursor::new(bytes);
     std::io::copy(&mut content, &mut file)?;
     info!("Downloaded file: {:?}", path);
     Ok(())
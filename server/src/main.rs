@@ -1,6 +1,7 @@
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, HeaderValue};
 use convert_case::{Case, Casing};
 use core::panic;
+use image::ImageDecoder;
 use log::{debug, error, info, warn};
 use sha2::{Digest, Sha256};
 use std::{
@@ -22,23 +23,73 @@ use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::sync::Mutex;
 
-use tokio::{fs::File, io::AsyncWriteExt};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
 
 use thiserror::Error;
 
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-/// This hashmap holds hashes for the files, so that twice the same file cannot be saved.
-static FILE_HASHES: Lazy<Mutex<HashMap<PathBuf, String>>> =
+/// A persisted record of a file's digest, plus the `mtime`/`size` it was computed from so
+/// `compute_initial_digests` can tell whether a file changed since the index was last saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DigestEntry {
+    hash: String,
+    mtime: u64,
+    size: u64,
+    /// Compact BlurHash placeholder for this image, recomputed only when the file changes.
+    /// `None` if the file couldn't be decoded as an image when last hashed.
+    blurhash: Option<String>,
+}
+
+/// This hashmap holds digests for the files, so that twice the same file cannot be saved.
+/// Backed on disk by `DIGEST_INDEX_PATH` so restarts don't require rehashing every file.
+static FILE_HASHES: Lazy<Mutex<HashMap<PathBuf, DigestEntry>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
 const ADDRESS: &str = "0.0.0.0:4000";
 const IMAGE_DIRECTORY: &str = "wallpapers";
+const VARIANT_DIRECTORY: &str = "wallpapers_variants";
+const DIGEST_INDEX_PATH: &str = "digest_index.json";
+
+/// Maps an image's path to the secret token required to delete it, so deletion isn't
+/// open to anyone who guesses a filename. Backed on disk by `DELETE_TOKEN_STORE_PATH`.
+static DELETE_TOKENS: Lazy<Mutex<HashMap<PathBuf, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+const DELETE_TOKEN_STORE_PATH: &str = "delete_tokens.json";
 
 const MAX_FILE_SIZE_BYTES: u64 = 1024 * 1024 * 30; // 30MiB
 const MAX_FILE_NAME_LENGTH: u64 = 255;
 
+/// Largest pixel width/height the `image` crate is allowed to decode to. Without this, a
+/// small upload (well under `MAX_FILE_SIZE_BYTES`) can declare enormous dimensions and blow
+/// up memory on decode (a classic decompression bomb).
+const MAX_DECODE_DIMENSION: u32 = 16384;
+
+/// Largest total allocation the `image` crate is allowed to make while decoding a single
+/// image, in bytes.
+const MAX_DECODE_ALLOC_BYTES: u64 = 512 * 1024 * 1024; // 512 MiB
+
+const MAX_VARIANT_DIMENSION: u32 = 4096;
+
+/// Cap on the total size of `VARIANT_DIRECTORY`. Since the cache key is derived from
+/// unauthenticated query parameters, without a cap a caller could enumerate distinct
+/// `w`/`h`/`format` combinations to grow it without bound.
+const MAX_VARIANT_CACHE_BYTES: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+/// Whether uploads are re-encoded on ingest to strip EXIF/ancillary metadata (GPS, camera
+/// serials, timestamps). Disable if operators want to preserve originals as-is.
+const STRIP_METADATA_ON_UPLOAD: bool = true;
+
+/// BlurHash grid size: a 4x3 grid of DCT components is plenty for a blurred placeholder.
+const BLURHASH_X_COMPONENTS: u32 = 4;
+const BLURHASH_Y_COMPONENTS: u32 = 3;
+
 #[tokio::main]
 async fn main() {
     setup_logging(log::LevelFilter::Debug);
@@ -49,12 +100,26 @@ async fn main() {
         panic!("Failed to create the directory or compute the file hashes: {e}");
     });
 
+    init_variant_directory(VARIANT_DIRECTORY).unwrap_or_else(|e| {
+        error!("Failed to create the variant cache directory: {e}");
+        panic!("Failed to create the variant cache directory: {e}");
+    });
+
+    init_delete_tokens().unwrap_or_else(|e| {
+        error!("Failed to load the delete-token store: {e}");
+        panic!("Failed to load the delete-token store: {e}");
+    });
+
     let app = Router::new()
         .route("/", get(serve_file))
         .route("/upload", post(upload_file))
         .route("/images", get(get_images))
         .route("/images/:filename", get(serve_image))
-        .route("/delete/:filename", axum::routing::delete(delete_image))
+        .route("/images/:filename/thumbnail", get(serve_thumbnail))
+        .route(
+            "/delete/:filename/:token",
+            axum::routing::delete(delete_image),
+        )
         .layer(DefaultBodyLimit::max(MAX_FILE_SIZE_BYTES as usize));
 
     let listener = tokio::net::TcpListener::bind(ADDRESS)
@@ -110,14 +175,159 @@ fn init_image_directory(image_directory: &str) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-/// Computes the SHA-256 digests of each file in the image directory and populates the global hash
-/// HashMap.
+/// Makes sure the derived-variant cache directory exists.
+fn init_variant_directory(variant_directory: &str) -> Result<(), std::io::Error> {
+    match fs::create_dir(variant_directory) {
+        Ok(_) => {
+            info!("Successfully created directory: {variant_directory}");
+            Ok(())
+        }
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                Ok(())
+            } else {
+                error!("Failed to create directory: {variant_directory}");
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Evicts the oldest cached variants (by mtime) until `VARIANT_DIRECTORY`, plus
+/// `incoming_bytes` for the entry about to be written, fits within `MAX_VARIANT_CACHE_BYTES`.
+fn evict_variant_cache_for(incoming_bytes: u64) -> Result<(), std::io::Error> {
+    let mut entries: Vec<(PathBuf, u64, std::time::SystemTime)> = fs::read_dir(VARIANT_DIRECTORY)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let modified = metadata
+                .modified()
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect();
+
+    let mut total_bytes: u64 = entries.iter().map(|(_, size, _)| size).sum::<u64>() + incoming_bytes;
+    if total_bytes <= MAX_VARIANT_CACHE_BYTES {
+        return Ok(());
+    }
+
+    entries.sort_by_key(|(_, _, modified)| *modified);
+
+    for (path, size, _) in entries {
+        if total_bytes <= MAX_VARIANT_CACHE_BYTES {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            debug!("Evicted cached variant to stay under budget: {:?}", path);
+            total_bytes = total_bytes.saturating_sub(size);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `evict_variant_cache_for` on a blocking thread, so the `fs::read_dir`/`metadata`/
+/// `remove_file` calls it makes don't stall a tokio worker on every cache-growth check —
+/// the same reasoning as `persist_digest_index`/`persist_delete_tokens`.
+async fn evict_variant_cache_for_blocking(incoming_bytes: u64) -> Result<(), std::io::Error> {
+    tokio::task::spawn_blocking(move || evict_variant_cache_for(incoming_bytes))
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+}
+
+/// Loads the persisted digest index from `DIGEST_INDEX_PATH`. Returns an empty map if the
+/// file doesn't exist yet or fails to parse (e.g. first boot, or a format change).
+fn load_digest_index() -> HashMap<PathBuf, DigestEntry> {
+    match fs::read_to_string(DIGEST_INDEX_PATH) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Failed to parse {DIGEST_INDEX_PATH}, starting from an empty index: {e}");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Persists the current digest index to `DIGEST_INDEX_PATH`.
+fn save_digest_index(index: &HashMap<PathBuf, DigestEntry>) -> Result<(), std::io::Error> {
+    let serialized = serde_json::to_vec_pretty(index)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    fs::write(DIGEST_INDEX_PATH, serialized)
+}
+
+/// Serializes and writes a snapshot of the digest index on a blocking thread, so the
+/// O(n) cost of persisting a large library's index doesn't stall a tokio worker on every
+/// upload/delete.
+async fn persist_digest_index(snapshot: HashMap<PathBuf, DigestEntry>) -> Result<(), std::io::Error> {
+    tokio::task::spawn_blocking(move || save_digest_index(&snapshot))
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+}
+
+/// Loads the persisted delete-token store from `DELETE_TOKEN_STORE_PATH` into `DELETE_TOKENS`.
+fn init_delete_tokens() -> Result<(), std::io::Error> {
+    let tokens = match fs::read_to_string(DELETE_TOKEN_STORE_PATH) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Failed to parse {DELETE_TOKEN_STORE_PATH}, starting from an empty store: {e}");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    };
+    *DELETE_TOKENS.lock().unwrap() = tokens;
+    Ok(())
+}
+
+/// Persists the current delete-token store to `DELETE_TOKEN_STORE_PATH`.
+fn save_delete_tokens(tokens: &HashMap<PathBuf, String>) -> Result<(), std::io::Error> {
+    let serialized = serde_json::to_vec_pretty(tokens)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    fs::write(DELETE_TOKEN_STORE_PATH, serialized)
+}
+
+/// Serializes and writes a snapshot of the delete-token store on a blocking thread, for the
+/// same reason as `persist_digest_index`.
+async fn persist_delete_tokens(snapshot: HashMap<PathBuf, String>) -> Result<(), std::io::Error> {
+    tokio::task::spawn_blocking(move || save_delete_tokens(&snapshot))
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+}
+
+/// Generates a random delete token for a freshly uploaded file and persists it.
+async fn generate_delete_token(path: &Path) -> Result<String, std::io::Error> {
+    let token = Uuid::new_v4().to_string();
+    let snapshot = {
+        let mut tokens = DELETE_TOKENS.lock().unwrap();
+        tokens.insert(path.to_path_buf(), token.clone());
+        tokens.clone()
+    };
+    persist_delete_tokens(snapshot).await?;
+    Ok(token)
+}
+
+/// Returns the on-disk `mtime` (as unix seconds) and size of a file.
+fn file_mtime_and_size(metadata: &std::fs::Metadata) -> (u64, u64) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    (mtime, metadata.len())
+}
+
+/// Loads the persisted digest index and populates the global hash HashMap, only rehashing
+/// files whose `mtime`/size changed since the index was last saved.
 fn compute_initial_digests(image_directory: &str) -> Result<(), std::io::Error> {
-    info!("Computing initial file digests... (might take some time)");
+    info!("Loading persisted digest index and checking for stale entries...");
 
-    // Computing file hashes
     let start = std::time::Instant::now();
+    let persisted = load_digest_index();
+    let mut updated: HashMap<PathBuf, DigestEntry> = HashMap::new();
     let mut file_count: usize = 0;
+    let mut rehashed_count: usize = 0;
     let mut total_megabytes_hashed: f64 = 0.0;
 
     let entries = fs::read_dir(image_directory)?;
@@ -128,26 +338,44 @@ fn compute_initial_digests(image_directory: &str) -> Result<(), std::io::Error>
         if !path.is_file() {
             continue;
         }
-
         file_count += 1;
-        let file_hash = compute_file_hash(path.to_str().expect("Error path to_str()"))?;
-        total_megabytes_hashed += file_hash.1 as f64 / 1024.0 / 1024.0;
 
-        // Insert filepath: filehash into the global HashSet
-        {
-            let mut map = FILE_HASHES.lock().unwrap();
-            map.insert(path, file_hash.0);
+        let (mtime, size) = file_mtime_and_size(&entry.metadata()?);
+
+        if let Some(entry) = persisted.get(&path) {
+            if entry.mtime == mtime && entry.size == size {
+                updated.insert(path, entry.clone());
+                continue;
+            }
         }
+
+        rehashed_count += 1;
+        let path_str = path.to_str().expect("Error path to_str()");
+        let (hash, bytes_hashed) = compute_file_hash(path_str)?;
+        total_megabytes_hashed += bytes_hashed as f64 / 1024.0 / 1024.0;
+        let blurhash = compute_blurhash(path_str);
+        updated.insert(
+            path,
+            DigestEntry {
+                hash,
+                mtime,
+                size,
+                blurhash,
+            },
+        );
     }
 
     let elapsed_time = (start.elapsed().as_secs_f64() * 10.0).round() / 10.0;
     let rounded_megabytes = (total_megabytes_hashed * 10.0).round() / 10.0;
 
     info!(
-        "Computed the hash of {} files in {:#?}s, totaling {:#?} MiB",
-        file_count, elapsed_time, rounded_megabytes
+        "Checked {} files, rehashed {} of them in {:#?}s, totaling {:#?} MiB",
+        file_count, rehashed_count, elapsed_time, rounded_megabytes
     );
 
+    *FILE_HASHES.lock().unwrap() = updated;
+    save_digest_index(&FILE_HASHES.lock().unwrap())?;
+
     Ok(())
 }
 
@@ -163,13 +391,78 @@ fn compute_file_hash(path: &str) -> Result<(String, u64), std::io::Error> {
     Ok((hex::encode(hash_bytes), bytes_hashed))
 }
 
-/// Adds the digest of a file into the global hash HashMap.
-fn add_digest(path: &str) -> Result<(), std::io::Error> {
+/// Decoding limits applied to every untrusted image we decode (uploads and on-disk
+/// wallpapers alike), bounding both the pixel dimensions and total allocation so a crafted
+/// file can't exhaust memory on decode.
+fn decode_limits() -> image::Limits {
+    let mut limits = image::Limits::no_limits();
+    limits.max_image_width = Some(MAX_DECODE_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC_BYTES);
+    limits
+}
+
+/// Computes a compact BlurHash placeholder for an image, so the web UI can render a
+/// blurred preview while the full wallpaper loads. Returns `None` if the file can't be
+/// decoded (it is still hashed for dedup purposes, it just won't have a placeholder).
+fn compute_blurhash(path: &str) -> Option<String> {
+    let image = match image::ImageReader::open(path).and_then(|mut reader| {
+        reader.limits(decode_limits());
+        reader.decode().map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+        })
+    }) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Failed to decode {path} for BlurHash computation: {e}");
+            return None;
+        }
+    };
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    match blurhash::encode(
+        BLURHASH_X_COMPONENTS,
+        BLURHASH_Y_COMPONENTS,
+        width,
+        height,
+        &rgba.into_raw(),
+    ) {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            warn!("Failed to compute BlurHash for {path}: {e}");
+            None
+        }
+    }
+}
+
+/// Adds the digest of a file into the global hash HashMap, and persists the updated index.
+async fn add_digest(path: &str) -> Result<(), std::io::Error> {
     match compute_file_hash(path) {
-        Ok(digest) => {
+        Ok((hash, size)) => {
+            let blurhash = compute_blurhash(path);
             let path = PathBuf::from(path);
-            FILE_HASHES.lock().unwrap().insert(path.clone(), digest.0);
-            info!("Added hash of file '{:?}'", path);
+            let mtime = fs::metadata(&path)
+                .ok()
+                .map(|m| file_mtime_and_size(&m).0)
+                .unwrap_or(0);
+
+            let snapshot = {
+                let mut map = FILE_HASHES.lock().unwrap();
+                map.insert(
+                    path.clone(),
+                    DigestEntry {
+                        hash,
+                        mtime,
+                        size,
+                        blurhash,
+                    },
+                );
+                map.clone()
+            };
+            persist_digest_index(snapshot).await?;
+
+            info!("Added digest of file '{:?}'", path);
             Ok(())
         }
         Err(e) => {
@@ -186,7 +479,7 @@ fn is_file_duplicate(path: &str) -> Result<bool, std::io::Error> {
         .lock()
         .unwrap()
         .values()
-        .any(|hash| hash == &digest.0))
+        .any(|entry| entry.hash == digest.0))
 }
 
 #[derive(Error, Debug)]
@@ -197,10 +490,16 @@ enum AppError {
     IoError(#[from] std::io::Error),
     #[error("File is not an image")]
     NotAnImage,
+    #[error("File content does not match its declared image format")]
+    CorruptImage,
     #[error("Filename too long")]
     FilenameTooLong,
     #[error("File too large (max is 30MB)")]
     FileTooLarge,
+    #[error("Image not found")]
+    ImageNotFound,
+    #[error("Invalid thumbnail parameters")]
+    InvalidParameters,
 }
 
 impl IntoResponse for AppError {
@@ -209,11 +508,17 @@ impl IntoResponse for AppError {
             Self::MultipartError(_) => (StatusCode::BAD_REQUEST, "Invalid form data"),
             Self::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Server error"),
             Self::NotAnImage => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "File is not an image"),
+            Self::CorruptImage => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "File content does not match its declared image format",
+            ),
             Self::FilenameTooLong => (StatusCode::BAD_REQUEST, "Filename too long"),
             Self::FileTooLarge => (
                 StatusCode::PAYLOAD_TOO_LARGE,
                 "File too large (max is 30MB)",
             ),
+            Self::ImageNotFound => (StatusCode::NOT_FOUND, "Image not found"),
+            Self::InvalidParameters => (StatusCode::BAD_REQUEST, "Invalid thumbnail parameters"),
         };
 
         let body = Json(json!({
@@ -234,6 +539,16 @@ fn is_valid_image_extension(filename: &str) -> bool {
     matches!(extension.as_str(), "jpg" | "jpeg" | "png" | "webp")
 }
 
+/// Maps a (lowercased) file extension to the `image` crate format it declares.
+fn extension_to_image_format(extension: &str) -> Option<image::ImageFormat> {
+    match extension {
+        "jpg" | "jpeg" => Some(image::ImageFormat::Jpeg),
+        "png" => Some(image::ImageFormat::Png),
+        "webp" => Some(image::ImageFormat::WebP),
+        _ => None,
+    }
+}
+
 fn generate_filename(filename_input: &str) -> Result<String, AppError> {
     let filename = sanitize(filename_input);
 
@@ -273,6 +588,7 @@ fn sanitize(text: &str) -> String {
 
 async fn upload_file(mut multipart: Multipart) -> Result<impl IntoResponse, AppError> {
     info!("Starting file upload process");
+    let mut uploaded: Vec<Value> = Vec::new();
     while let Some(field) = multipart.next_field().await? {
         debug!("Processing new field from multipart form");
         if field.name() != Some("wallpaper") {
@@ -301,12 +617,21 @@ async fn upload_file(mut multipart: Multipart) -> Result<impl IntoResponse, AppE
         let final_filepath = Path::new(IMAGE_DIRECTORY).join(&final_filename);
         debug!("Final filepath: {:#?}", final_filepath);
 
+        let declared_format = extension_to_image_format(
+            &final_filename
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .to_lowercase(),
+        )
+        .ok_or(AppError::NotAnImage)?;
+
         let file = File::create_new(&final_filepath).await.map_err(|e| {
             error!("Failed to create new file: {:?}", final_filepath);
             e
         })?;
 
-        match upload_and_save(field, file).await {
+        match upload_and_save(field, file, declared_format).await {
             Ok(_) => {
                 info!("Successfully saved file: {:?}", final_filepath);
                 Ok(())
@@ -326,31 +651,115 @@ async fn upload_file(mut multipart: Multipart) -> Result<impl IntoResponse, AppE
             tokio::fs::remove_file(&final_filepath).await?;
             debug!("Removed duplicate file: {:?}", final_filepath);
         } else {
-            add_digest(&final_filepath.to_string_lossy())?;
+            add_digest(&final_filepath.to_string_lossy()).await?;
             debug!("Added file digest {:?} to HashMap", final_filepath);
+
+            let delete_token = generate_delete_token(&final_filepath).await?;
+            uploaded.push(json!({
+                "filename": final_filename,
+                "delete_token": delete_token,
+            }));
         }
     }
 
-    Ok(Json(json!({"message": "File uploaded successfully"})))
+    Ok(Json(json!({"message": "File uploaded successfully", "files": uploaded})))
 }
 
 /// Uploads and then saves the file onto the machine's fs.
+///
+/// While buffering the bytes, also sniffs the magic bytes and decodes the image to
+/// make sure its real content matches `declared_format`, rejecting content-type spoofing
+/// and malformed files before they ever land in `IMAGE_DIRECTORY`.
 async fn upload_and_save(
     mut field: axum::extract::multipart::Field<'_>,
     mut file: File,
+    declared_format: image::ImageFormat,
 ) -> Result<(), AppError> {
     let mut file_size: u64 = 0;
+    let mut buffer: Vec<u8> = Vec::new();
     while let Some(chunk) = field.chunk().await? {
         file_size += chunk.len() as u64;
         if file_size > MAX_FILE_SIZE_BYTES {
             debug!("{file_size} & {MAX_FILE_SIZE_BYTES}");
             return Err(AppError::FileTooLarge);
         }
+        buffer.extend_from_slice(&chunk);
         file.write_all(&chunk).await?;
     }
 
     file.sync_all().await?;
 
+    let detected_format = image::guess_format(&buffer).map_err(|e| {
+        warn!("Failed to sniff magic bytes of uploaded file: {e}");
+        AppError::NotAnImage
+    })?;
+
+    if detected_format != declared_format {
+        warn!(
+            "Declared format {:?} does not match detected format {:?}",
+            declared_format, detected_format
+        );
+        return Err(AppError::NotAnImage);
+    }
+
+    let image = decode_oriented_image(&buffer, detected_format)?;
+
+    if STRIP_METADATA_ON_UPLOAD {
+        strip_metadata(&mut file, &image, detected_format).await?;
+    }
+
+    Ok(())
+}
+
+/// Decodes `buffer` as `format` and applies its EXIF orientation tag, so portrait/rotated
+/// photos come out right-side up once that metadata is later stripped. Kept synchronous
+/// (and called before any `.await`) because `image::ImageDecoder` implementations aren't
+/// `Send`, so holding one across an await would make the caller's future `!Send`.
+fn decode_oriented_image(
+    buffer: &[u8],
+    format: image::ImageFormat,
+) -> Result<image::DynamicImage, AppError> {
+    let mut reader = image::ImageReader::new(std::io::Cursor::new(buffer));
+    reader.set_format(format);
+    reader.limits(decode_limits());
+
+    let mut decoder = reader.into_decoder().map_err(|e| {
+        warn!("Uploaded file failed to decode as a genuine image: {e}");
+        AppError::CorruptImage
+    })?;
+    let orientation = decoder
+        .orientation()
+        .unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut image = image::DynamicImage::from_decoder(decoder).map_err(|e| {
+        warn!("Uploaded file failed to decode as a genuine image: {e}");
+        AppError::CorruptImage
+    })?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Re-encodes the decoded image and overwrites `file` with it, discarding any EXIF/ancillary
+/// metadata (GPS coordinates, camera serials, timestamps) the source carried. The dedup hash
+/// computed afterwards by the caller is over this cleaned content, so identical cleaned
+/// images dedup correctly regardless of their original metadata.
+async fn strip_metadata(
+    file: &mut File,
+    image: &image::DynamicImage,
+    format: image::ImageFormat,
+) -> Result<(), AppError> {
+    let mut cleaned: Vec<u8> = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut cleaned), format)
+        .map_err(|e| {
+            warn!("Failed to re-encode image while stripping metadata: {e}");
+            AppError::CorruptImage
+        })?;
+
+    file.seek(std::io::SeekFrom::Start(0)).await?;
+    file.set_len(0).await?;
+    file.write_all(&cleaned).await?;
+    file.sync_all().await?;
+
     Ok(())
 }
 
@@ -369,7 +778,16 @@ async fn get_images() -> Result<Json<Value>, StatusCode> {
         if let Ok(file_type) = entry.file_type().await {
             if file_type.is_file() {
                 if let Some(file_name) = entry.file_name().to_str() {
-                    images.push(file_name.to_string());
+                    let blurhash = FILE_HASHES
+                        .lock()
+                        .unwrap()
+                        .get(&entry.path())
+                        .and_then(|digest| digest.blurhash.clone());
+
+                    images.push(json!({
+                        "filename": file_name,
+                        "blurhash": blurhash,
+                    }));
                 }
             }
         }
@@ -378,37 +796,352 @@ async fn get_images() -> Result<Json<Value>, StatusCode> {
     Ok(Json(json!({"images": images})))
 }
 
+const CACHE_MAX_AGE_SECS: u64 = 60 * 60 * 24; // 1 day
+
+/// How much of a file a `Range` header asked for.
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    /// No (valid) `Range` header was present: serve the whole file.
+    Full,
+    /// A satisfiable `bytes=start-end` range (inclusive on both ends).
+    Range(u64, u64),
+    /// A `Range` header was present but could not be satisfied for this file size.
+    Unsatisfiable,
+}
+
+/// Parses a single-range `Range: bytes=...` header against `file_size`.
+/// Multiple ranges (`bytes=0-10,20-30`) are not supported; only the first is honored.
+fn parse_range_header(range_header: &str, file_size: u64) -> RangeRequest {
+    let Some(spec) = range_header.strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    let spec = spec.split(',').next().unwrap_or("").trim();
+    let Some((start_str, end_str)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    if start_str.is_empty() {
+        // Suffix range: "-N" means the last N bytes of the file.
+        let Ok(suffix_len) = end_str.parse::<u64>() else {
+            return RangeRequest::Full;
+        };
+        if suffix_len == 0 || file_size == 0 {
+            return RangeRequest::Unsatisfiable;
+        }
+        let start = file_size.saturating_sub(suffix_len);
+        return RangeRequest::Range(start, file_size - 1);
+    }
+
+    let Ok(start) = start_str.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+
+    let end = if end_str.is_empty() {
+        file_size.saturating_sub(1)
+    } else {
+        match end_str.parse::<u64>() {
+            Ok(end) => end.min(file_size.saturating_sub(1)),
+            Err(_) => return RangeRequest::Full,
+        }
+    };
+
+    if file_size == 0 || start >= file_size || start > end {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Range(start, end)
+}
+
+/// Returns whether the request's conditional headers (`If-None-Match` takes priority over
+/// `If-Modified-Since`, per RFC 7232) indicate the client's cached copy is still fresh.
+fn is_not_modified(headers: &HeaderMap, etag: &str, modified: std::time::SystemTime) -> bool {
+    if let Some(if_none_match) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        return if_none_match
+            .split(',')
+            .any(|tag| tag.trim() == etag || tag.trim() == "*");
+    }
+
+    if let Some(if_modified_since) = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+    {
+        if let Ok(since) = httpdate::parse_http_date(if_modified_since) {
+            return modified <= since;
+        }
+    }
+
+    false
+}
+
 /// Provides a shortcut from addr/wallpapers/img.jpg to addr/images/img.jpg
+///
+/// Supports `Range` requests (`206 Partial Content`) so browsers and the sync client can
+/// resume or seek large wallpapers, and honors `If-Modified-Since`/`If-None-Match` with a
+/// `304 Not Modified` so unchanged files aren't re-read on every request.
 async fn serve_image(
     axum::extract::Path(filename): axum::extract::Path<String>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     let path = PathBuf::from(IMAGE_DIRECTORY).join(&filename);
-    match tokio::fs::read(path).await {
-        Ok(contents) => {
-            let content_type = mime_guess::from_path(&filename).first_or_octet_stream();
-            ([(header::CONTENT_TYPE, content_type.as_ref())], contents).into_response()
+
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|_| AppError::ImageNotFound)?;
+    let file_size = metadata.len();
+    let modified = metadata
+        .modified()
+        .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+    let last_modified = httpdate::fmt_http_date(modified);
+    let etag = format!(
+        "\"{}-{file_size}\"",
+        modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    );
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(
+        header::LAST_MODIFIED,
+        HeaderValue::from_str(&last_modified).unwrap(),
+    );
+    response_headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={CACHE_MAX_AGE_SECS}")).unwrap(),
+    );
+
+    if is_not_modified(&headers, &etag, modified) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let content_type = mime_guess::from_path(&filename).first_or_octet_stream();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(content_type.as_ref()).unwrap(),
+    );
+
+    match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range_header) => match parse_range_header(range_header, file_size) {
+            RangeRequest::Range(start, end) => {
+                let mut file = tokio::fs::File::open(&path)
+                    .await
+                    .map_err(|_| AppError::ImageNotFound)?;
+                file.seek(std::io::SeekFrom::Start(start)).await?;
+
+                let range_len = end - start + 1;
+                let mut buffer = vec![0u8; range_len as usize];
+                file.read_exact(&mut buffer).await?;
+
+                response_headers.insert(
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes {start}-{end}/{file_size}")).unwrap(),
+                );
+
+                Ok((StatusCode::PARTIAL_CONTENT, response_headers, buffer).into_response())
+            }
+            RangeRequest::Unsatisfiable => {
+                response_headers.insert(
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{file_size}")).unwrap(),
+                );
+                Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response())
+            }
+            RangeRequest::Full => {
+                let contents = tokio::fs::read(&path).await?;
+                Ok((StatusCode::OK, response_headers, contents).into_response())
+            }
+        },
+        None => {
+            let contents = tokio::fs::read(&path).await?;
+            Ok((StatusCode::OK, response_headers, contents).into_response())
+        }
+    }
+}
+
+/// Builds a canonical operation string from the thumbnail query parameters, so that
+/// `w=400&h=300` and `h=300&w=400` normalize to the same cache entry.
+/// Only these query parameters affect the generated variant. Anything else in the query
+/// string is ignored so it can't be used to mint unbounded extra cache entries.
+const THUMBNAIL_PARAM_ALLOWLIST: [&str; 3] = ["w", "h", "format"];
+
+fn canonical_operation_string(params: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<(&str, &String)> = THUMBNAIL_PARAM_ALLOWLIST
+        .iter()
+        .filter_map(|&key| params.get(key).map(|value| (key, value)))
+        .collect();
+    pairs.sort_by(|a, b| a.0.cmp(b.0));
+    pairs
+        .into_iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Serves a resized/converted variant of a wallpaper, generating and caching it on first
+/// request. Subsequent requests for the same `filename` and query parameters are served
+/// straight from `VARIANT_DIRECTORY`.
+async fn serve_thumbnail(
+    axum::extract::Path(filename): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, AppError> {
+    let source_path = Path::new(IMAGE_DIRECTORY).join(&filename);
+    if !source_path.is_file() {
+        return Err(AppError::ImageNotFound);
+    }
+
+    let source_extension = source_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let output_format = match params.get("format") {
+        Some(format) => {
+            extension_to_image_format(&format.to_lowercase()).ok_or(AppError::InvalidParameters)?
+        }
+        None => extension_to_image_format(&source_extension).ok_or(AppError::InvalidParameters)?,
+    };
+    let output_extension = output_format
+        .extensions_str()
+        .first()
+        .ok_or(AppError::InvalidParameters)?;
+
+    let operation_string = canonical_operation_string(&params);
+    // The source digest is already computed once by `compute_initial_digests`/`add_digest`
+    // and kept in FILE_HASHES; reuse it instead of re-hashing the (up to 30 MiB) source file
+    // on every thumbnail request, including cache hits.
+    let source_hash = FILE_HASHES
+        .lock()
+        .unwrap()
+        .get(&source_path)
+        .map(|entry| entry.hash.clone())
+        .ok_or(AppError::ImageNotFound)?;
+    let variant_filename = format!("{source_hash}-{operation_string}.{output_extension}");
+    let variant_path = Path::new(VARIANT_DIRECTORY).join(&variant_filename);
+
+    if let Ok(cached) = tokio::fs::read(&variant_path).await {
+        debug!("Serving cached variant: {:?}", variant_path);
+        let content_type = mime_guess::from_path(&variant_path).first_or_octet_stream();
+        return Ok(([(header::CONTENT_TYPE, content_type.as_ref())], cached).into_response());
+    }
+
+    let width = parse_dimension(params.get("w"))?;
+    let height = parse_dimension(params.get("h"))?;
+
+    let source_bytes = tokio::fs::read(&source_path).await?;
+    let mut image = image::load_from_memory(&source_bytes).map_err(|e| {
+        error!("Failed to decode source image {:?}: {e}", source_path);
+        AppError::CorruptImage
+    })?;
+
+    if let (Some(w), Some(h)) = (width, height) {
+        image = image.resize_to_fill(w, h, image::imageops::FilterType::Lanczos3);
+    } else if let Some(w) = width {
+        image = image.resize(w, u32::MAX, image::imageops::FilterType::Lanczos3);
+    } else if let Some(h) = height {
+        image = image.resize(u32::MAX, h, image::imageops::FilterType::Lanczos3);
+    }
+
+    let mut encoded: Vec<u8> = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut encoded), output_format)
+        .map_err(|e| {
+            error!("Failed to encode variant for {:?}: {e}", source_path);
+            AppError::CorruptImage
+        })?;
+
+    evict_variant_cache_for_blocking(encoded.len() as u64).await?;
+    tokio::fs::write(&variant_path, &encoded).await?;
+    debug!("Cached new variant: {:?}", variant_path);
+
+    let content_type = mime_guess::from_path(&variant_path).first_or_octet_stream();
+    Ok(([(header::CONTENT_TYPE, content_type.as_ref())], encoded).into_response())
+}
+
+/// Parses an optional `w`/`h` query parameter into a bounded pixel dimension.
+fn parse_dimension(raw: Option<&String>) -> Result<Option<u32>, AppError> {
+    match raw {
+        None => Ok(None),
+        Some(raw) => {
+            let value: u32 = raw.parse().map_err(|_| AppError::InvalidParameters)?;
+            if value == 0 || value > MAX_VARIANT_DIMENSION {
+                return Err(AppError::InvalidParameters);
+            }
+            Ok(Some(value))
         }
-        Err(_) => Json(json!({"error": "Image not found"})).into_response(),
     }
 }
 
 /// Deletes the image on the fs from its path.
+/// Compares two secrets in constant time, to avoid leaking how many leading bytes of a
+/// guessed delete token matched via response-timing side channels.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 async fn delete_image(
-    axum::extract::Path(filename): axum::extract::Path<String>,
+    axum::extract::Path((filename, token)): axum::extract::Path<(String, String)>,
 ) -> impl IntoResponse {
-    let file_path = Path::new(IMAGE_DIRECTORY).join(&filename);
-    match fs::remove_file(file_path) {
+    // `filename` must be a bare basename: reject anything that could let a percent-decoded
+    // path segment escape IMAGE_DIRECTORY, so the token check and the deletion below are
+    // guaranteed to operate on the exact same path.
+    if filename.contains('/') || filename.contains("..") {
+        warn!("Rejected delete: unsafe filename {:?}", filename);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid filename"})),
+        )
+            .into_response();
+    }
+
+    let path = Path::new(IMAGE_DIRECTORY).join(&filename);
+
+    match DELETE_TOKENS.lock().unwrap().get(&path).cloned() {
+        Some(stored_token) if constant_time_eq(&stored_token, &token) => {}
+        _ => {
+            warn!("Rejected delete for {:?}: invalid delete token", filename);
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({"error": "Invalid delete token"})),
+            )
+                .into_response();
+        }
+    }
+
+    match fs::remove_file(&path) {
         Ok(_) => {
-            let key = PathBuf::from(format!(
-                "{IMAGE_DIRECTORY}/{}",
-                filename.split('/').last().unwrap()
-            ));
-
-            // Remove the hash from FILE_HASHES
-            if FILE_HASHES.lock().unwrap().remove(&key).is_some() {
-                debug!("Removed hash with file: {:?}", filename);
-            } else {
-                debug!("Failed to remove hash with file: {:?}", filename);
+            // Remove the digest from FILE_HASHES and persist the updated index.
+            let digest_snapshot = {
+                let mut map = FILE_HASHES.lock().unwrap();
+                map.remove(&path).map(|_| map.clone())
+            };
+            match digest_snapshot {
+                Some(snapshot) => {
+                    if let Err(e) = persist_digest_index(snapshot).await {
+                        error!("Failed to persist digest index after deletion: {e}");
+                    }
+                    debug!("Removed digest with file: {:?}", filename);
+                }
+                None => debug!("Failed to remove digest with file: {:?}", filename),
+            }
+
+            // Remove the delete token itself so it can't be replayed.
+            let tokens_snapshot = {
+                let mut tokens = DELETE_TOKENS.lock().unwrap();
+                tokens.remove(&path);
+                tokens.clone()
+            };
+            if let Err(e) = persist_delete_tokens(tokens_snapshot).await {
+                error!("Failed to persist delete-token store after deletion: {e}");
             }
 
             info!("Removed file: {:?}", filename);
@@ -417,3 +1150,113 @@ async fn delete_image(
         Err(_) => Json(json!({"error": "Image not found"})).into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_header_full_file_when_absent_or_invalid() {
+        assert_eq!(parse_range_header("not-bytes=0-10", 100), RangeRequest::Full);
+        assert_eq!(parse_range_header("bytes=abc-10", 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn range_header_parses_start_end() {
+        assert_eq!(parse_range_header("bytes=0-9", 100), RangeRequest::Range(0, 9));
+    }
+
+    #[test]
+    fn range_header_clamps_end_to_file_size() {
+        assert_eq!(
+            parse_range_header("bytes=0-999", 100),
+            RangeRequest::Range(0, 99)
+        );
+    }
+
+    #[test]
+    fn range_header_open_ended_goes_to_end_of_file() {
+        assert_eq!(
+            parse_range_header("bytes=50-", 100),
+            RangeRequest::Range(50, 99)
+        );
+    }
+
+    #[test]
+    fn range_header_suffix_takes_last_n_bytes() {
+        assert_eq!(
+            parse_range_header("bytes=-10", 100),
+            RangeRequest::Range(90, 99)
+        );
+    }
+
+    #[test]
+    fn range_header_unsatisfiable_when_start_past_end_of_file() {
+        assert_eq!(
+            parse_range_header("bytes=200-300", 100),
+            RangeRequest::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn range_header_unsatisfiable_for_empty_file() {
+        assert_eq!(parse_range_header("bytes=0-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn range_header_only_honors_first_range_in_a_list() {
+        assert_eq!(
+            parse_range_header("bytes=0-9,20-30", 100),
+            RangeRequest::Range(0, 9)
+        );
+    }
+
+    #[test]
+    fn canonical_operation_string_orders_params_regardless_of_insertion_order() {
+        let mut a = HashMap::new();
+        a.insert("h".to_string(), "300".to_string());
+        a.insert("w".to_string(), "400".to_string());
+
+        let mut b = HashMap::new();
+        b.insert("w".to_string(), "400".to_string());
+        b.insert("h".to_string(), "300".to_string());
+
+        assert_eq!(canonical_operation_string(&a), canonical_operation_string(&b));
+        assert_eq!(canonical_operation_string(&a), "h=300&w=400");
+    }
+
+    #[test]
+    fn canonical_operation_string_ignores_non_allowlisted_params() {
+        let mut params = HashMap::new();
+        params.insert("w".to_string(), "400".to_string());
+        params.insert("evil".to_string(), "payload".to_string());
+
+        assert_eq!(canonical_operation_string(&params), "w=400");
+    }
+
+    #[test]
+    fn canonical_operation_string_empty_when_no_recognized_params() {
+        let params = HashMap::new();
+        assert_eq!(canonical_operation_string(&params), "");
+    }
+
+    #[test]
+    fn constant_time_eq_matches_identical_strings() {
+        assert!(constant_time_eq("delete-token-123", "delete-token-123"));
+    }
+
+    #[test]
+    fn constant_time_eq_rejects_different_strings_of_same_length() {
+        assert!(!constant_time_eq("delete-token-123", "delete-token-124"));
+    }
+
+    #[test]
+    fn constant_time_eq_rejects_different_lengths() {
+        assert!(!constant_time_eq("short", "much-longer-string"));
+    }
+
+    #[test]
+    fn constant_time_eq_empty_strings_are_equal() {
+        assert!(constant_time_eq("", ""));
+    }
+}